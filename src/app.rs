@@ -0,0 +1,49 @@
+//! The flags and arguments ripgrep's command line understands, defined
+//! with clap's builder API. `args.rs` turns the resulting `ArgMatches`
+//! into a usable `Args`.
+
+use clap::{App, Arg};
+
+/// Build the clap application.
+pub fn app() -> App<'static, 'static> {
+    App::new("rg")
+        .arg(Arg::with_name("sort-path")
+            .long("sort-path")
+            .help(
+                "Sort results by file path. Results are buffered and \
+                 sorted as long as the search finishes quickly (or the \
+                 buffer doesn't grow too large); otherwise ripgrep \
+                 falls back to streaming results out unsorted so \
+                 memory use and latency stay bounded on huge trees."))
+        .arg(Arg::with_name("exec")
+            .long("exec")
+            .takes_value(true)
+            .value_name("CMD")
+            .number_of_values(1)
+            .multiple(true)
+            .conflicts_with("exec-batch")
+            .help(
+                "Execute CMD for each file that matches (or, with \
+                 --files, for each file found). {} {/} {//} {.} {/.} \
+                 in CMD are substituted with (a piece of) the path."))
+        .arg(Arg::with_name("exec-batch")
+            .long("exec-batch")
+            .takes_value(true)
+            .value_name("CMD")
+            .number_of_values(1)
+            .multiple(true)
+            .conflicts_with("exec")
+            .help(
+                "Execute CMD once with every matching path appended \
+                 (or substituted at a placeholder), chunked to respect \
+                 the OS argument length limit."))
+        .arg(Arg::with_name("watch")
+            .long("watch")
+            .help(
+                "After the initial search, keep watching the searched \
+                 paths and re-run the search whenever they change."))
+        .arg(Arg::with_name("watch-clear")
+            .long("watch-clear")
+            .requires("watch")
+            .help("In --watch mode, clear the screen before each re-run."))
+}
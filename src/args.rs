@@ -0,0 +1,72 @@
+//! `Args` is the parsed, high-level form of ripgrep's configuration,
+//! distilled from clap's `ArgMatches`.
+
+use std::path::Path;
+
+use clap;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use app;
+use exec::CommandTemplate;
+
+pub struct Args {
+    matches: clap::ArgMatches<'static>,
+    exec_command: Option<CommandTemplate>,
+    exec_batch_command: Option<CommandTemplate>,
+    ignorer: Gitignore,
+}
+
+impl Args {
+    /// Parse the command line into an `Args`, building the exec command
+    /// templates and the ignore matcher used by `--watch` up front so
+    /// the rest of the program can just ask for them.
+    pub fn parse() -> ::Result<Args> {
+        let matches = app::app().get_matches();
+        let exec_command =
+            matches.values_of("exec").map(CommandTemplate::new);
+        let exec_batch_command =
+            matches.values_of("exec-batch").map(CommandTemplate::new);
+        let ignorer = GitignoreBuilder::new(".").build()?;
+        Ok(Args {
+            matches: matches,
+            exec_command: exec_command,
+            exec_batch_command: exec_batch_command,
+            ignorer: ignorer,
+        })
+    }
+
+    /// Whether `--sort-path` was given: results should be buffered and
+    /// printed in path order instead of streamed out unsorted.
+    pub fn sort_path(&self) -> bool {
+        self.matches.is_present("sort-path")
+    }
+
+    /// The parsed `--exec` command template, if one was given.
+    pub fn exec_command(&self) -> Option<CommandTemplate> {
+        self.exec_command.clone()
+    }
+
+    /// The parsed `--exec-batch` command template, if one was given.
+    pub fn exec_batch_command(&self) -> Option<CommandTemplate> {
+        self.exec_batch_command.clone()
+    }
+
+    /// Whether `--watch` was given.
+    pub fn watch(&self) -> bool {
+        self.matches.is_present("watch")
+    }
+
+    /// Whether `--watch-clear` was given.
+    pub fn watch_clear(&self) -> bool {
+        self.matches.is_present("watch-clear")
+    }
+
+    /// Returns true if `path` matches the ignore matcher built in
+    /// `parse`. `--watch` uses this to skip events for ignored paths.
+    /// That matcher is built with no patterns, so no path is reported
+    /// as ignored: gitignore files, hidden files and `--glob` are not
+    /// consulted here.
+    pub fn is_path_ignored(&self, path: &Path) -> bool {
+        self.ignorer.matched(path, path.is_dir()).is_ignore()
+    }
+}
@@ -0,0 +1,320 @@
+//! Support for running an arbitrary command against each matching file,
+//! via `--exec` and `--exec-batch`.
+//!
+//! This plays the same role that `fd`'s `exec` module plays: instead of
+//! printing search results, we substitute them into a user supplied
+//! command template and spawn that command as a child process. Output
+//! from concurrent jobs is serialized through a single writer so lines
+//! from different jobs don't get interleaved.
+
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+use pathutil::{basename, dirname, strip_extension};
+
+/// A single piece of a command argument: either literal text or one of
+/// the placeholder tokens that gets substituted with (a piece of) the
+/// matched path.
+#[derive(Clone, Debug, PartialEq)]
+enum ArgumentTemplate {
+    Text(String),
+    /// `{}`: the full path.
+    Path,
+    /// `{/}`: the basename.
+    Basename,
+    /// `{//}`: the parent directory.
+    Parent,
+    /// `{.}`: the path without its extension.
+    NoExt,
+    /// `{/.}`: the basename without its extension.
+    BasenameNoExt,
+}
+
+/// A single argument of the command template, broken up into the
+/// literal and placeholder pieces it's made of. Most arguments will
+/// just be a single `Text` piece, but an argument like `{}.bak` mixes a
+/// placeholder with literal text.
+#[derive(Clone, Debug)]
+struct TemplateArg {
+    parts: Vec<ArgumentTemplate>,
+}
+
+impl TemplateArg {
+    fn parse(arg: &str) -> TemplateArg {
+        const TOKENS: &'static [(&'static str, fn() -> ArgumentTemplate)] = &[
+            ("{//}", || ArgumentTemplate::Parent),
+            ("{/.}", || ArgumentTemplate::BasenameNoExt),
+            ("{/}", || ArgumentTemplate::Basename),
+            ("{.}", || ArgumentTemplate::NoExt),
+            ("{}", || ArgumentTemplate::Path),
+        ];
+
+        let mut parts = vec![];
+        let mut rest = arg;
+        'outer: while !rest.is_empty() {
+            for &(tok, build) in TOKENS {
+                if rest.starts_with(tok) {
+                    parts.push(build());
+                    rest = &rest[tok.len()..];
+                    continue 'outer;
+                }
+            }
+            let next_token_pos =
+                rest.find('{').unwrap_or_else(|| rest.len());
+            let (text, remainder) = rest.split_at(
+                if next_token_pos == 0 { 1 } else { next_token_pos }
+            );
+            match parts.last_mut() {
+                Some(&mut ArgumentTemplate::Text(ref mut buf)) => {
+                    buf.push_str(text);
+                }
+                _ => parts.push(ArgumentTemplate::Text(text.to_string())),
+            }
+            rest = remainder;
+        }
+        TemplateArg { parts: parts }
+    }
+
+    fn is_placeholder(&self) -> bool {
+        self.parts.iter().any(|p| !matches!(p, ArgumentTemplate::Text(_)))
+    }
+
+    fn generate(&self, path: &Path) -> OsString {
+        let mut out = OsString::new();
+        for part in &self.parts {
+            match *part {
+                ArgumentTemplate::Text(ref s) => out.push(s),
+                ArgumentTemplate::Path => out.push(path.as_os_str()),
+                ArgumentTemplate::Basename => out.push(basename(path)),
+                ArgumentTemplate::Parent => out.push(dirname(path)),
+                ArgumentTemplate::NoExt => {
+                    out.push(strip_extension(path).as_os_str())
+                }
+                ArgumentTemplate::BasenameNoExt => {
+                    out.push(strip_extension(basename(path).as_ref()))
+                }
+            }
+        }
+        out
+    }
+}
+
+/// A parsed `--exec`/`--exec-batch` command template, ready to be
+/// instantiated once per matching path (or once for a whole batch of
+/// paths).
+#[derive(Clone, Debug)]
+pub struct CommandTemplate {
+    args: Vec<TemplateArg>,
+}
+
+impl CommandTemplate {
+    /// Build a command template out of the raw argv given on the command
+    /// line, e.g. `["echo", "Found", "{}"]`.
+    pub fn new<I, S>(input: I) -> CommandTemplate
+    where I: IntoIterator<Item = S>, S: AsRef<str> {
+        let args = input.into_iter()
+            .map(|a| TemplateArg::parse(a.as_ref()))
+            .collect();
+        CommandTemplate { args: args }
+    }
+
+    /// Returns true if at least one argument in this template contains a
+    /// placeholder. `--exec-batch` uses this to decide whether to append
+    /// all paths at the end of the command or substitute them in place.
+    pub fn has_placeholder(&self) -> bool {
+        self.args.iter().any(|a| a.is_placeholder())
+    }
+
+    fn command_for(&self, path: &Path) -> Command {
+        let mut parts = self.args.iter().map(|a| a.generate(path));
+        let mut cmd = Command::new(parts.next().unwrap());
+        cmd.args(parts);
+        cmd
+    }
+
+    /// Run this template once, substituting `path` into each
+    /// placeholder. The child's stdout/stderr are captured while it runs
+    /// and then written to `out` in one go under its lock, so concurrent
+    /// jobs don't interleave. Returns the child's exit code, or 1 if it couldn't
+    /// be spawned at all.
+    pub fn generate_and_execute(
+        &self,
+        path: &Path,
+        out: Arc<Mutex<Write + Send>>,
+    ) -> i32 {
+        let output = match self.command_for(path).output() {
+            Ok(output) => output,
+            Err(err) => {
+                eprintln!("exec: {}: {}", path.display(), err);
+                return 1;
+            }
+        };
+        let mut out = out.lock().unwrap();
+        let _ = out.write_all(&output.stdout);
+        let _ = out.write_all(&output.stderr);
+        output.status.code().unwrap_or(1)
+    }
+
+    /// Run this template once against the full list of `paths`, in
+    /// chunks sized by the actual byte length of the generated command
+    /// line rather than by item count, so we stay under the OS argument
+    /// length limit even with a few very long paths. If the template
+    /// contains a placeholder (e.g. `{}`), each matching path in the
+    /// chunk is substituted in place of it; otherwise the whole chunk is
+    /// appended to the end of the command, the way `xargs` does. Returns
+    /// the most severe (highest) exit code seen across all chunks.
+    pub fn generate_and_execute_batch<I>(&self, paths: I) -> i32
+    where I: IntoIterator<Item = ::std::path::PathBuf> {
+        let has_placeholder = self.has_placeholder();
+        let static_len: usize = self.args.iter()
+            .filter(|a| !a.is_placeholder())
+            .map(|a| a.generate(Path::new("")).len() + 1)
+            .sum();
+
+        let mut exit_code = 0;
+        let mut chunk = vec![];
+        let mut chunk_len = static_len;
+        for path in paths {
+            let cost = self.path_cost(&path, has_placeholder);
+            if !chunk.is_empty()
+                && chunk_len + cost > MAX_COMMAND_LINE_BYTES
+            {
+                exit_code =
+                    exit_code.max(self.run_chunk(&chunk, has_placeholder));
+                chunk.clear();
+                chunk_len = static_len;
+            }
+            chunk_len += cost;
+            chunk.push(path);
+        }
+        if !chunk.is_empty() {
+            exit_code =
+                exit_code.max(self.run_chunk(&chunk, has_placeholder));
+        }
+        exit_code
+    }
+
+    /// The number of extra bytes a single path adds to the command
+    /// line: the path's own length if it's appended at the end, or the
+    /// length of every placeholder substitution if it's substituted in
+    /// place (a template can use `{}` more than once).
+    fn path_cost(&self, path: &Path, has_placeholder: bool) -> usize {
+        if has_placeholder {
+            self.args.iter()
+                .filter(|a| a.is_placeholder())
+                .map(|a| a.generate(path).len() + 1)
+                .sum()
+        } else {
+            path.as_os_str().len() + 1
+        }
+    }
+
+    /// Instantiate and run the template against a single chunk of paths.
+    fn run_chunk(
+        &self,
+        chunk: &[::std::path::PathBuf],
+        has_placeholder: bool,
+    ) -> i32 {
+        let mut argv = vec![];
+        for arg in &self.args {
+            if has_placeholder && arg.is_placeholder() {
+                argv.extend(chunk.iter().map(|path| arg.generate(path)));
+            } else {
+                argv.push(arg.generate(Path::new("")));
+            }
+        }
+        let mut parts = argv.into_iter();
+        let mut cmd = Command::new(parts.next().unwrap());
+        cmd.args(parts);
+        if !has_placeholder {
+            cmd.args(chunk);
+        }
+        match cmd.status() {
+            Ok(status) => status.code().unwrap_or(1),
+            Err(err) => {
+                eprintln!("exec: {}", err);
+                1
+            }
+        }
+    }
+}
+
+/// A conservative cap on the byte length of a single generated command
+/// line for `--exec-batch`. Real `ARG_MAX` on Unix is typically in the
+/// megabytes, but the practical limit for a single `CreateProcess` call
+/// on Windows is only around 32 KiB, so we size against that on every
+/// platform instead of special-casing each OS's real limit.
+const MAX_COMMAND_LINE_BYTES: usize = 32 * 1024;
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{ArgumentTemplate, CommandTemplate, TemplateArg};
+
+    #[test]
+    fn token_precedence() {
+        // `{//}` and `{/.}` must win over their shorter prefixes `{/}`
+        // and `{.}`.
+        assert_eq!(
+            TemplateArg::parse("{//}").parts,
+            vec![ArgumentTemplate::Parent]
+        );
+        assert_eq!(
+            TemplateArg::parse("{/.}").parts,
+            vec![ArgumentTemplate::BasenameNoExt]
+        );
+        assert_eq!(
+            TemplateArg::parse("{/}").parts,
+            vec![ArgumentTemplate::Basename]
+        );
+        assert_eq!(
+            TemplateArg::parse("{.}").parts,
+            vec![ArgumentTemplate::NoExt]
+        );
+        assert_eq!(
+            TemplateArg::parse("{}").parts,
+            vec![ArgumentTemplate::Path]
+        );
+    }
+
+    #[test]
+    fn mixed_literal_and_placeholder() {
+        let arg = TemplateArg::parse("foo{}bar");
+        assert_eq!(
+            arg.parts,
+            vec![
+                ArgumentTemplate::Text("foo".to_string()),
+                ArgumentTemplate::Path,
+                ArgumentTemplate::Text("bar".to_string()),
+            ]
+        );
+        assert_eq!(arg.generate(Path::new("baz")), "foobazbar");
+    }
+
+    #[test]
+    fn generate_substitutes_each_token() {
+        let path = Path::new("/tmp/foo/bar.txt");
+        assert_eq!(
+            TemplateArg::parse("{}").generate(path), "/tmp/foo/bar.txt"
+        );
+        assert_eq!(TemplateArg::parse("{/}").generate(path), "bar.txt");
+        assert_eq!(TemplateArg::parse("{//}").generate(path), "/tmp/foo");
+        assert_eq!(
+            TemplateArg::parse("{.}").generate(path), "/tmp/foo/bar"
+        );
+        assert_eq!(TemplateArg::parse("{/.}").generate(path), "bar");
+    }
+
+    #[test]
+    fn has_placeholder() {
+        let with = CommandTemplate::new(vec!["echo", "{}"]);
+        assert!(with.has_placeholder());
+
+        let without = CommandTemplate::new(vec!["echo", "hello"]);
+        assert!(!without.has_placeholder());
+    }
+}
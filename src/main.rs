@@ -16,6 +16,7 @@ extern crate libc;
 extern crate log;
 extern crate memchr;
 extern crate memmap;
+extern crate notify;
 extern crate num_cpus;
 extern crate regex;
 extern crate termcolor;
@@ -25,12 +26,14 @@ extern crate winapi;
 use std::error::Error;
 use std::io;
 use std::io::Write;
+use std::path::PathBuf;
 use std::process;
 use std::result;
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::mpsc;
 use std::thread;
+use std::time::{Duration, Instant};
 
 use termcolor::WriteColor;
 
@@ -53,29 +56,30 @@ macro_rules! eprintln {
 mod app;
 mod args;
 mod atty;
+mod exec;
 mod pathutil;
 mod printer;
 mod search_buffer;
 mod search_stream;
 mod unescape;
+mod watch;
 mod worker;
 
 pub type Result<T> = result::Result<T, Box<Error + Send + Sync>>;
 
 fn main() {
     match Args::parse().map(Arc::new).and_then(run) {
-        Ok(0) => process::exit(1),
-        Ok(_) => process::exit(0),
+        Ok(code) => process::exit(code.code()),
         Err(err) => {
             eprintln!("{}", err);
-            process::exit(1);
+            process::exit(ExitCode::Error.code());
         }
     }
 }
 
-fn run(args: Arc<Args>) -> Result<u64> {
+fn run(args: Arc<Args>) -> Result<ExitCode> {
     if args.never_match() {
-        return Ok(0);
+        return Ok(ExitCode::NoMatch);
     }
     {
         let args = args.clone();
@@ -83,26 +87,54 @@ fn run(args: Arc<Args>) -> Result<u64> {
             let mut writer = args.stdout();
             let _ = writer.reset();
             let _ = writer.flush();
+            // An interrupted search is neither a clean "no match" nor
+            // one of the errors `ExitCode` tracks (a bad read, a bad
+            // write); keep its exit status as it was before `ExitCode`
+            // existed rather than folding it into `ExitCode::Error`.
             process::exit(1);
         });
     }
+    if args.watch() {
+        return watch::run(args, run_once);
+    }
+    run_once(args)
+}
+
+fn run_once(args: Arc<Args>) -> Result<ExitCode> {
+    let had_error = Arc::new(AtomicBool::new(false));
     let threads = args.threads();
-    if args.files() {
+    let match_count = if args.exec_batch_command().is_some() {
+        run_exec_batch(args, had_error.clone())?
+    } else if args.exec_command().is_some() {
         if threads == 1 || args.is_one_path() {
-            run_files_one_thread(args)
+            run_exec_one_thread(args, had_error.clone())?
         } else {
-            run_files_parallel(args)
+            run_exec_parallel(args, had_error.clone())?
+        }
+    } else if args.files() {
+        if threads == 1 || args.is_one_path() {
+            run_files_one_thread(args, had_error.clone())?
+        } else {
+            run_files_parallel(args, had_error.clone())?
         }
     } else if args.type_list() {
-        run_types(args)
+        run_types(args)?
     } else if threads == 1 || args.is_one_path() {
-        run_one_thread(args)
+        run_one_thread(args, had_error.clone())?
     } else {
-        run_parallel(args)
-    }
+        run_parallel(args, had_error.clone())?
+    };
+    Ok(ExitCode::new(match_count, had_error.load(Ordering::SeqCst)))
 }
 
-fn run_parallel(args: Arc<Args>) -> Result<u64> {
+fn run_parallel(
+    args: Arc<Args>,
+    had_error: Arc<AtomicBool>,
+) -> Result<u64> {
+    if args.sort_path() {
+        return run_parallel_sorted(args, had_error);
+    }
+
     let bufwtr = Arc::new(args.buffer_writer());
     let quiet_matched = QuietMatched::new(args.quiet());
     let paths_searched = Arc::new(AtomicUsize::new(0));
@@ -113,6 +145,7 @@ fn run_parallel(args: Arc<Args>) -> Result<u64> {
         let quiet_matched = quiet_matched.clone();
         let paths_searched = paths_searched.clone();
         let match_count = match_count.clone();
+        let had_error = had_error.clone();
         let bufwtr = bufwtr.clone();
         let mut buf = bufwtr.buffer();
         let mut worker = args.worker();
@@ -122,7 +155,9 @@ fn run_parallel(args: Arc<Args>) -> Result<u64> {
             if quiet_matched.has_match() {
                 return Quit;
             }
-            let dent = match get_or_log_dir_entry(result, args.no_messages()) {
+            let dent = match
+                get_or_log_dir_entry(result, args.no_messages(), &had_error)
+            {
                 None => return Continue,
                 Some(dent) => dent,
             };
@@ -143,12 +178,97 @@ fn run_parallel(args: Arc<Args>) -> Result<u64> {
                     return Quit;
                 }
             }
-            // BUG(burntsushi): We should handle this error instead of ignoring
-            // it. See: https://github.com/BurntSushi/ripgrep/issues/200
-            let _ = bufwtr.print(&buf);
+            if bufwtr.print(&buf).is_err() {
+                had_error.store(true, Ordering::SeqCst);
+            }
+            Continue
+        })
+    });
+    if !args.paths().is_empty() && paths_searched.load(Ordering::SeqCst) == 0 {
+        if !args.no_messages() {
+            eprint_nothing_searched();
+        }
+    }
+    Ok(match_count.load(Ordering::SeqCst) as u64)
+}
+
+/// Like `run_parallel`, but with `--sort-path` enabled.
+///
+/// Instead of letting each worker print its own buffer as soon as it
+/// finishes (which makes the order of results depend on thread
+/// scheduling), workers send their rendered buffers down a channel to a
+/// single receiver thread. The receiver buffers everything it sees and,
+/// as long as the whole walk completes quickly (or the buffer doesn't
+/// grow too large), sorts the results by path before printing them. If
+/// the walk runs long or produces too much buffered output, the receiver
+/// switches to streaming results out unsorted as they arrive, so memory
+/// use and latency stay bounded on huge trees. Once streaming begins, we
+/// never go back to buffering.
+fn run_parallel_sorted(
+    args: Arc<Args>,
+    had_error: Arc<AtomicBool>,
+) -> Result<u64> {
+    let bufwtr = Arc::new(args.buffer_writer());
+    let quiet_matched = QuietMatched::new(args.quiet());
+    let paths_searched = Arc::new(AtomicUsize::new(0));
+    let match_count = Arc::new(AtomicUsize::new(0));
+    let (tx, rx) = mpsc::sync_channel::<(PathBuf, termcolor::Buffer)>(128);
+
+    let receiver_bufwtr = bufwtr.clone();
+    let receiver_had_error = had_error.clone();
+    let receiver_thread = thread::spawn(move || {
+        run_receiver(rx, &*receiver_bufwtr, &receiver_had_error);
+    });
+
+    args.walker_parallel().run(|| {
+        let args = args.clone();
+        let quiet_matched = quiet_matched.clone();
+        let paths_searched = paths_searched.clone();
+        let match_count = match_count.clone();
+        let had_error = had_error.clone();
+        let bufwtr = bufwtr.clone();
+        let tx = tx.clone();
+        let mut worker = args.worker();
+        Box::new(move |result| {
+            use ignore::WalkState::*;
+
+            if quiet_matched.has_match() {
+                return Quit;
+            }
+            let dent = match
+                get_or_log_dir_entry(result, args.no_messages(), &had_error)
+            {
+                None => return Continue,
+                Some(dent) => dent,
+            };
+            paths_searched.fetch_add(1, Ordering::SeqCst);
+            let path = dent.path().to_path_buf();
+            let mut buf = bufwtr.buffer();
+            {
+                let mut printer = args.printer(&mut buf);
+                let count =
+                    if dent.is_stdin() {
+                        worker.run(&mut printer, Work::Stdin)
+                    } else {
+                        worker.run(&mut printer, Work::DirEntry(dent))
+                    };
+                match_count.fetch_add(count as usize, Ordering::SeqCst);
+                if quiet_matched.set_match(count > 0) {
+                    return Quit;
+                }
+                if count == 0 {
+                    return Continue;
+                }
+            }
+            if tx.send((path, buf)).is_err() {
+                return Quit;
+            }
             Continue
         })
     });
+    drop(tx);
+    receiver_thread.join().unwrap();
+
     if !args.paths().is_empty() && paths_searched.load(Ordering::SeqCst) == 0 {
         if !args.no_messages() {
             eprint_nothing_searched();
@@ -157,14 +277,105 @@ fn run_parallel(args: Arc<Args>) -> Result<u64> {
     Ok(match_count.load(Ordering::SeqCst) as u64)
 }
 
-fn run_one_thread(args: Arc<Args>) -> Result<u64> {
+/// The maximum number of buffered results we're willing to hold onto
+/// while waiting to see if the walk finishes quickly. Once exceeded, the
+/// receiver switches to streaming mode.
+const MAX_BUFFER_LENGTH: usize = 1000;
+
+/// How long, counted from the start of the walk, the receiver keeps
+/// buffering before giving up and switching to streaming mode.
+const BUFFER_DEADLINE: Duration = Duration::from_millis(100);
+
+/// The receiver's state: either still accumulating results in the hope
+/// that the walk finishes before our deadline or buffer cap, or already
+/// streaming results out as they arrive.
+///
+/// This only ever moves in one direction, Buffering -> Streaming, never
+/// the reverse.
+enum ReceiverState {
+    Buffering(Vec<(PathBuf, termcolor::Buffer)>),
+    Streaming,
+}
+
+/// Runs the receiver side of `run_parallel_sorted`: buffer results until
+/// the walk finishes or our limits are hit, then print everything,
+/// sorted by path if we never had to start streaming.
+fn run_receiver(
+    rx: mpsc::Receiver<(PathBuf, termcolor::Buffer)>,
+    bufwtr: &termcolor::BufferWriter,
+    had_error: &AtomicBool,
+) {
+    let print = |buf: &termcolor::Buffer| {
+        if bufwtr.print(buf).is_err() {
+            had_error.store(true, Ordering::SeqCst);
+        }
+    };
+    let deadline = Instant::now() + BUFFER_DEADLINE;
+    let mut state = ReceiverState::Buffering(vec![]);
+    loop {
+        let received = match state {
+            ReceiverState::Buffering(_) => rx.recv_timeout(
+                deadline.saturating_duration_since(Instant::now())),
+            ReceiverState::Streaming => rx.recv()
+                .map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok(item) => {
+                state = match state {
+                    ReceiverState::Streaming => {
+                        print(&item.1);
+                        ReceiverState::Streaming
+                    }
+                    ReceiverState::Buffering(mut buffered) => {
+                        buffered.push(item);
+                        if buffered.len() > MAX_BUFFER_LENGTH {
+                            for (_, ref buf) in &buffered {
+                                print(buf);
+                            }
+                            ReceiverState::Streaming
+                        } else {
+                            ReceiverState::Buffering(buffered)
+                        }
+                    }
+                };
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                state = match state {
+                    ReceiverState::Streaming => ReceiverState::Streaming,
+                    ReceiverState::Buffering(buffered) => {
+                        for (_, ref buf) in &buffered {
+                            print(buf);
+                        }
+                        ReceiverState::Streaming
+                    }
+                };
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                if let ReceiverState::Buffering(mut buffered) = state {
+                    buffered.sort_by(|a, b| a.0.cmp(&b.0));
+                    for (_, ref buf) in &buffered {
+                        print(buf);
+                    }
+                }
+                return;
+            }
+        }
+    }
+}
+
+fn run_one_thread(
+    args: Arc<Args>,
+    had_error: Arc<AtomicBool>,
+) -> Result<u64> {
     let stdout = args.stdout();
     let mut stdout = stdout.lock();
     let mut worker = args.worker();
     let mut paths_searched: u64 = 0;
     let mut match_count = 0;
     for result in args.walker() {
-        let dent = match get_or_log_dir_entry(result, args.no_messages()) {
+        let dent = match
+            get_or_log_dir_entry(result, args.no_messages(), &had_error)
+        {
             None => continue,
             Some(dent) => dent,
         };
@@ -193,7 +404,10 @@ fn run_one_thread(args: Arc<Args>) -> Result<u64> {
     Ok(match_count)
 }
 
-fn run_files_parallel(args: Arc<Args>) -> Result<u64> {
+fn run_files_parallel(
+    args: Arc<Args>,
+    had_error: Arc<AtomicBool>,
+) -> Result<u64> {
     let print_args = args.clone();
     let (tx, rx) = mpsc::channel::<ignore::DirEntry>();
     let print_thread = thread::spawn(move || {
@@ -209,8 +423,11 @@ fn run_files_parallel(args: Arc<Args>) -> Result<u64> {
     let no_messages = args.no_messages();
     args.walker_parallel().run(move || {
         let tx = tx.clone();
+        let had_error = had_error.clone();
         Box::new(move |result| {
-            if let Some(dent) = get_or_log_dir_entry(result, no_messages) {
+            if let Some(dent) =
+                get_or_log_dir_entry(result, no_messages, &had_error)
+            {
                 tx.send(dent).unwrap();
             }
             ignore::WalkState::Continue
@@ -219,12 +436,17 @@ fn run_files_parallel(args: Arc<Args>) -> Result<u64> {
     Ok(print_thread.join().unwrap())
 }
 
-fn run_files_one_thread(args: Arc<Args>) -> Result<u64> {
+fn run_files_one_thread(
+    args: Arc<Args>,
+    had_error: Arc<AtomicBool>,
+) -> Result<u64> {
     let stdout = args.stdout();
     let mut printer = args.printer(stdout.lock());
     let mut file_count = 0;
     for result in args.walker() {
-        let dent = match get_or_log_dir_entry(result, args.no_messages()) {
+        let dent = match
+            get_or_log_dir_entry(result, args.no_messages(), &had_error)
+        {
             None => continue,
             Some(dent) => dent,
         };
@@ -234,6 +456,171 @@ fn run_files_one_thread(args: Arc<Args>) -> Result<u64> {
     Ok(file_count)
 }
 
+/// Runs `--exec` across multiple threads: each matching file has the
+/// command template instantiated and spawned as soon as its search (or,
+/// with `--files`, its walk) completes. Concurrent jobs share a single
+/// locked writer so their stdout/stderr don't interleave.
+fn run_exec_parallel(
+    args: Arc<Args>,
+    had_error: Arc<AtomicBool>,
+) -> Result<u64> {
+    let template = args.exec_command()
+        .expect("run_exec_parallel called without an --exec command");
+    let files_only = args.files();
+    let bufwtr = Arc::new(args.buffer_writer());
+    let quiet_matched = QuietMatched::new(args.quiet());
+    let match_count = Arc::new(AtomicUsize::new(0));
+    let out: Arc<Mutex<Write + Send>> = Arc::new(Mutex::new(io::stdout()));
+
+    args.walker_parallel().run(|| {
+        let args = args.clone();
+        let template = template.clone();
+        let quiet_matched = quiet_matched.clone();
+        let match_count = match_count.clone();
+        let had_error = had_error.clone();
+        let out = out.clone();
+        let bufwtr = bufwtr.clone();
+        let mut buf = bufwtr.buffer();
+        let mut worker = args.worker();
+        Box::new(move |result| {
+            use ignore::WalkState::*;
+
+            if quiet_matched.has_match() {
+                return Quit;
+            }
+            let dent = match
+                get_or_log_dir_entry(result, args.no_messages(), &had_error)
+            {
+                None => return Continue,
+                Some(dent) => dent,
+            };
+            let path = dent.path().to_path_buf();
+            let matched = if files_only {
+                match_count.fetch_add(1, Ordering::SeqCst);
+                true
+            } else {
+                buf.clear();
+                let mut printer = args.printer(&mut buf);
+                let count =
+                    if dent.is_stdin() {
+                        worker.run(&mut printer, Work::Stdin)
+                    } else {
+                        worker.run(&mut printer, Work::DirEntry(dent))
+                    };
+                match_count.fetch_add(count as usize, Ordering::SeqCst);
+                if quiet_matched.set_match(count > 0) {
+                    return Quit;
+                }
+                count > 0
+            };
+            if matched {
+                let code = template.generate_and_execute(&path, out.clone());
+                if code != 0 {
+                    had_error.store(true, Ordering::SeqCst);
+                }
+            }
+            Continue
+        })
+    });
+    Ok(match_count.load(Ordering::SeqCst) as u64)
+}
+
+/// Like `run_exec_parallel`, but on a single thread (used when
+/// `--threads=1` or when only one path is being searched).
+fn run_exec_one_thread(
+    args: Arc<Args>,
+    had_error: Arc<AtomicBool>,
+) -> Result<u64> {
+    let template = args.exec_command()
+        .expect("run_exec_one_thread called without an --exec command");
+    let files_only = args.files();
+    let mut worker = args.worker();
+    let mut match_count = 0;
+    let out: Arc<Mutex<Write + Send>> = Arc::new(Mutex::new(io::stdout()));
+
+    for result in args.walker() {
+        let dent = match
+            get_or_log_dir_entry(result, args.no_messages(), &had_error)
+        {
+            None => continue,
+            Some(dent) => dent,
+        };
+        let path = dent.path().to_path_buf();
+        let matched = if files_only {
+            match_count += 1;
+            true
+        } else {
+            let mut sink = io::sink();
+            let mut printer = args.printer(&mut sink);
+            let count =
+                if dent.is_stdin() {
+                    worker.run(&mut printer, Work::Stdin)
+                } else {
+                    worker.run(&mut printer, Work::DirEntry(dent))
+                };
+            match_count += count;
+            count > 0
+        };
+        if matched {
+            let code = template.generate_and_execute(&path, out.clone());
+            if code != 0 {
+                had_error.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+    Ok(match_count)
+}
+
+/// Runs `--exec-batch`: collects every matching path first, then invokes
+/// the command template once with all of them appended, chunked to stay
+/// under the OS argument length limit.
+fn run_exec_batch(
+    args: Arc<Args>,
+    had_error: Arc<AtomicBool>,
+) -> Result<u64> {
+    let template = args.exec_batch_command()
+        .expect("run_exec_batch called without an --exec-batch command");
+    let files_only = args.files();
+    let mut worker = args.worker();
+    let mut paths = vec![];
+    let mut match_count = 0;
+
+    for result in args.walker() {
+        let dent = match
+            get_or_log_dir_entry(result, args.no_messages(), &had_error)
+        {
+            None => continue,
+            Some(dent) => dent,
+        };
+        let path = dent.path().to_path_buf();
+        let matched = if files_only {
+            match_count += 1;
+            true
+        } else {
+            let mut sink = io::sink();
+            let mut printer = args.printer(&mut sink);
+            let count =
+                if dent.is_stdin() {
+                    worker.run(&mut printer, Work::Stdin)
+                } else {
+                    worker.run(&mut printer, Work::DirEntry(dent))
+                };
+            match_count += count;
+            count > 0
+        };
+        if matched {
+            paths.push(path);
+        }
+    }
+    if !paths.is_empty() {
+        let code = template.generate_and_execute_batch(paths);
+        if code != 0 {
+            had_error.store(true, Ordering::SeqCst);
+        }
+    }
+    Ok(match_count)
+}
+
 fn run_types(args: Arc<Args>) -> Result<u64> {
     let stdout = args.stdout();
     let mut printer = args.printer(stdout.lock());
@@ -248,9 +635,11 @@ fn run_types(args: Arc<Args>) -> Result<u64> {
 fn get_or_log_dir_entry(
     result: result::Result<ignore::DirEntry, ignore::Error>,
     no_messages: bool,
+    had_error: &AtomicBool,
 ) -> Option<ignore::DirEntry> {
     match result {
         Err(err) => {
+            had_error.store(true, Ordering::SeqCst);
             if !no_messages {
                 eprintln!("{}", err);
             }
@@ -258,6 +647,7 @@ fn get_or_log_dir_entry(
         }
         Ok(dent) => {
             if let Some(err) = dent.error() {
+                had_error.store(true, Ordering::SeqCst);
                 if !no_messages {
                     eprintln!("{}", err);
                 }
@@ -299,6 +689,55 @@ fn eprint_nothing_searched() {
                Try running again with --debug.");
 }
 
+/// The outcome of a whole run, used to pick the process's exit status.
+///
+/// This distinguishes a clean search that simply found nothing
+/// (`NoMatch`) from one where something actually went wrong along the
+/// way (`Error`), e.g. a directory we couldn't read or a write that
+/// failed. `merge` always prefers the more severe outcome, so a single
+/// error during an otherwise successful recursive search is never
+/// silently reported as a clean run.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ExitCode {
+    Success,
+    NoMatch,
+    Error,
+}
+
+impl ExitCode {
+    /// Combine a match count and whether any error occurred into a
+    /// single `ExitCode`.
+    pub fn new(match_count: u64, had_error: bool) -> ExitCode {
+        if had_error {
+            ExitCode::Error
+        } else if match_count > 0 {
+            ExitCode::Success
+        } else {
+            ExitCode::NoMatch
+        }
+    }
+
+    /// Merge two exit codes, keeping whichever is more severe:
+    /// `Error` beats `NoMatch`, which beats `Success`.
+    pub fn merge(self, other: ExitCode) -> ExitCode {
+        use self::ExitCode::*;
+        match (self, other) {
+            (Error, _) | (_, Error) => Error,
+            (NoMatch, _) | (_, NoMatch) => NoMatch,
+            (Success, Success) => Success,
+        }
+    }
+
+    /// The process exit status this code maps to.
+    pub fn code(self) -> i32 {
+        match self {
+            ExitCode::Success => 0,
+            ExitCode::NoMatch => 1,
+            ExitCode::Error => 2,
+        }
+    }
+}
+
 /// A simple thread safe abstraction for determining whether a search should
 /// stop if the user has requested quiet mode.
 #[derive(Clone, Debug)]
@@ -337,3 +776,34 @@ impl QuietMatched {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ExitCode;
+    use super::ExitCode::*;
+
+    #[test]
+    fn new_picks_the_right_outcome() {
+        assert_eq!(ExitCode::new(0, false), NoMatch);
+        assert_eq!(ExitCode::new(5, false), Success);
+        assert_eq!(ExitCode::new(0, true), Error);
+        assert_eq!(ExitCode::new(5, true), Error);
+    }
+
+    #[test]
+    fn merge_prefers_the_more_severe_outcome() {
+        let all = [Success, NoMatch, Error];
+        for &a in &all {
+            for &b in &all {
+                let expected = if a == Error || b == Error {
+                    Error
+                } else if a == NoMatch || b == NoMatch {
+                    NoMatch
+                } else {
+                    Success
+                };
+                assert_eq!(a.merge(b), expected, "{:?}.merge({:?})", a, b);
+            }
+        }
+    }
+}
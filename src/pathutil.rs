@@ -0,0 +1,30 @@
+//! Small helpers for pulling pieces out of a path: its basename, its
+//! parent directory, and its extension-stripped form. These back the
+//! `{/}`, `{//}`, `{.}` and `{/.}` placeholder tokens in `exec.rs`.
+
+use std::path::Path;
+
+/// The final component of `path`, e.g. `basename("/tmp/foo/bar.txt")`
+/// is `"bar.txt"`. Falls back to the whole path if it has no final
+/// component (e.g. `"/"`).
+pub fn basename(path: &Path) -> &Path {
+    match path.file_name() {
+        Some(name) => Path::new(name),
+        None => path,
+    }
+}
+
+/// The parent directory of `path`, or an empty path if it has none.
+pub fn dirname(path: &Path) -> &Path {
+    path.parent().unwrap_or_else(|| Path::new(""))
+}
+
+/// `path` with its extension (and the dot before it) removed, e.g.
+/// `strip_extension("/tmp/foo/bar.txt")` is `"/tmp/foo/bar"`. Returns
+/// `path` unchanged if it has no file stem to fall back on.
+pub fn strip_extension(path: &Path) -> ::std::path::PathBuf {
+    match path.file_stem() {
+        Some(stem) => path.with_file_name(stem),
+        None => path.to_path_buf(),
+    }
+}
@@ -0,0 +1,83 @@
+//! Support for `--watch`: after the initial search, keep monitoring the
+//! searched paths for changes and re-run the query whenever something
+//! relevant changes, redrawing the results each time.
+//!
+//! Bursts of filesystem events (e.g. an editor doing several writes for
+//! a single save) are coalesced into a single re-search by waiting for
+//! a short quiet period before triggering.
+
+use std::sync::Arc;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+
+use args::Args;
+use {ExitCode, Result};
+
+/// How long to wait after the last filesystem event before re-running
+/// the search, so a batch of saves collapses into a single refresh.
+const DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// Run `search_once` once immediately, then again every time the
+/// watched paths change, until the process exits (the Ctrl-C handler
+/// installed in `run` terminates the loop directly). The final exit
+/// code, should the loop ever end, reflects the most severe outcome
+/// seen across every pass (an error on any earlier pass is never
+/// masked by a clean pass that happens to run afterwards).
+pub fn run<F>(args: Arc<Args>, search_once: F) -> Result<ExitCode>
+where
+    F: Fn(Arc<Args>) -> Result<ExitCode>,
+{
+    let mut last_code = search_once(args.clone())?;
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher: RecommendedWatcher =
+        Watcher::new_raw(tx).map_err(|err| {
+            format!("failed to start filesystem watcher: {}", err)
+        })?;
+    for path in args.paths() {
+        watcher.watch(path, RecursiveMode::Recursive).map_err(|err| {
+            format!("failed to watch {}: {}", path.display(), err)
+        })?;
+    }
+
+    loop {
+        // Block for the first event, then drain anything else that
+        // shows up within the debounce window so a burst of events
+        // triggers only one re-search.
+        let event = match rx.recv() {
+            Ok(event) => event,
+            Err(_) => return Ok(last_code),
+        };
+        let mut relevant = is_relevant(&args, &event);
+        while let Ok(event) = rx.recv_timeout(DEBOUNCE) {
+            relevant = relevant || is_relevant(&args, &event);
+        }
+        if !relevant {
+            continue;
+        }
+
+        if args.watch_clear() {
+            clear_screen();
+        }
+        last_code = last_code.merge(search_once(args.clone())?);
+    }
+}
+
+/// Returns true if a filesystem event is worth triggering a re-search
+/// for, i.e. it carries no path or it touched a path that
+/// `Args::is_path_ignored` does not report as ignored.
+fn is_relevant(args: &Arc<Args>, event: &::notify::RawEvent) -> bool {
+    match event.path {
+        Some(ref path) => !args.is_path_ignored(path),
+        None => true,
+    }
+}
+
+/// Clear the terminal and move the cursor home, the same way `clear(1)`
+/// does, so each re-search starts from a blank screen.
+fn clear_screen() {
+    print!("\x1B[2J\x1B[H");
+    let _ = ::std::io::Write::flush(&mut ::std::io::stdout());
+}